@@ -0,0 +1,232 @@
+use std::{
+    fs::{self, File},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use clap::ValueEnum;
+use log::info;
+use zip::{CompressionMethod, ZipWriter, write::SimpleFileOptions};
+
+/// How a downloaded chapter directory is bundled once its pages are on disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Leave the per-chapter directories of numbered images as-is.
+    Dir,
+    /// A comic book archive: a zip of the page images in order.
+    Cbz,
+    /// A minimal reflowable EPUB with one full-bleed page per image.
+    Epub,
+}
+
+impl OutputFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Dir => "",
+            OutputFormat::Cbz => "cbz",
+            OutputFormat::Epub => "epub",
+        }
+    }
+}
+
+/// A chapter's ordered page images, grouped under its title.
+struct Section {
+    title: String,
+    pages: Vec<PathBuf>,
+}
+
+/// Bundles the chapter directories under `manga_path` into `format` archives.
+///
+/// With `merge` set, every chapter is concatenated into a single archive named
+/// after `display_title`; otherwise each chapter becomes its own archive next to
+/// its directory. [`OutputFormat::Dir`] is a no-op.
+pub fn package(
+    manga_path: &Path,
+    chapter_titles: &[String],
+    display_title: &str,
+    format: OutputFormat,
+    merge: bool,
+) -> anyhow::Result<()> {
+    if format == OutputFormat::Dir {
+        return Ok(());
+    }
+
+    let section = |title: &str| -> anyhow::Result<Section> {
+        Ok(Section {
+            title: title.to_string(),
+            pages: sorted_pages(&manga_path.join(title))?,
+        })
+    };
+
+    if merge {
+        let sections = chapter_titles
+            .iter()
+            .map(|title| section(title))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        // The display title is raw `<h1>` text; normalize path-hostile chars the
+        // same way chapter directory names are before using it as a file name.
+        let file_stem = display_title.replace('/', "-");
+        let archive_path = manga_path.join(format!("{file_stem}.{}", format.extension()));
+        write_archive(&archive_path, display_title, sections, format)?;
+        info!("packaged {} chapters into {}", chapter_titles.len(), archive_path.display());
+    } else {
+        for title in chapter_titles {
+            let archive_path = manga_path.join(format!("{title}.{}", format.extension()));
+            write_archive(&archive_path, title, vec![section(title)?], format)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the `NNN.jpg` page files in a chapter directory, sorted by page number.
+fn sorted_pages(chapter_dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut pages: Vec<PathBuf> = fs::read_dir(chapter_dir)
+        .with_context(|| format!("reading chapter directory {}", chapter_dir.display()))?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "jpg"))
+        .collect();
+    pages.sort();
+    Ok(pages)
+}
+
+fn write_archive(
+    archive_path: &Path,
+    display_title: &str,
+    sections: Vec<Section>,
+    format: OutputFormat,
+) -> anyhow::Result<()> {
+    match format {
+        OutputFormat::Cbz => write_cbz(archive_path, sections),
+        OutputFormat::Epub => write_epub(archive_path, display_title, sections),
+        OutputFormat::Dir => Ok(()),
+    }
+}
+
+fn write_cbz(archive_path: &Path, sections: Vec<Section>) -> anyhow::Result<()> {
+    let mut zip = ZipWriter::new(File::create(archive_path)?);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    // When several chapters share one archive, keep each under its own folder so
+    // the pages stay in reading order across chapter boundaries.
+    let multi = sections.len() > 1;
+    for section in &sections {
+        for page in &section.pages {
+            let file_name = page.file_name().unwrap_or_default().to_string_lossy();
+            let entry = if multi {
+                format!("{}/{file_name}", section.title)
+            } else {
+                file_name.into_owned()
+            };
+            zip.start_file(entry, options)?;
+            zip.write_all(&fs::read(page)?)?;
+        }
+    }
+
+    zip.finish()?;
+    Ok(())
+}
+
+const CONTAINER_XML: &str = r#"<?xml version="1.0"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#;
+
+fn write_epub(
+    archive_path: &Path,
+    display_title: &str,
+    sections: Vec<Section>,
+) -> anyhow::Result<()> {
+    let mut zip = ZipWriter::new(File::create(archive_path)?);
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    // The mimetype entry must be first and uncompressed per the EPUB spec.
+    zip.start_file("mimetype", stored)?;
+    zip.write_all(b"application/epub+zip")?;
+
+    zip.start_file("META-INF/container.xml", deflated)?;
+    zip.write_all(CONTAINER_XML.as_bytes())?;
+
+    let mut manifest = String::new();
+    let mut spine = String::new();
+    let mut payloads: Vec<(String, Vec<u8>)> = Vec::new();
+
+    let mut page_num = 0;
+    for section in &sections {
+        for page in &section.pages {
+            page_num += 1;
+            let image_href = format!("images/{page_num:04}.jpg");
+            let page_href = format!("page_{page_num:04}.xhtml");
+
+            manifest.push_str(&format!(
+                "    <item id=\"img{page_num:04}\" href=\"{image_href}\" media-type=\"image/jpeg\"/>\n"
+            ));
+            manifest.push_str(&format!(
+                "    <item id=\"page{page_num:04}\" href=\"{page_href}\" media-type=\"application/xhtml+xml\"/>\n"
+            ));
+            spine.push_str(&format!("    <itemref idref=\"page{page_num:04}\"/>\n"));
+
+            payloads.push((format!("OEBPS/{image_href}"), fs::read(page)?));
+            payloads.push((
+                format!("OEBPS/{page_href}"),
+                xhtml_page(display_title, &image_href).into_bytes(),
+            ));
+        }
+    }
+
+    let opf = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="bookid">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:title>{title}</dc:title>
+    <dc:identifier id="bookid">{title}</dc:identifier>
+    <dc:language>en</dc:language>
+  </metadata>
+  <manifest>
+{manifest}  </manifest>
+  <spine>
+{spine}  </spine>
+</package>
+"#,
+        title = escape_xml(display_title),
+    );
+
+    zip.start_file("OEBPS/content.opf", deflated)?;
+    zip.write_all(opf.as_bytes())?;
+
+    for (name, data) in payloads {
+        zip.start_file(name, deflated)?;
+        zip.write_all(&data)?;
+    }
+
+    zip.finish()?;
+    Ok(())
+}
+
+fn xhtml_page(title: &str, image_href: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml">
+  <head>
+    <title>{title}</title>
+    <style>html,body{{margin:0;padding:0;}} img{{display:block;width:100%;height:auto;}}</style>
+  </head>
+  <body><img src="{image_href}" alt=""/></body>
+</html>
+"#,
+        title = escape_xml(title),
+    )
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
@@ -1,6 +1,8 @@
 use std::{
     path::{Path, PathBuf},
     str::FromStr,
+    sync::Arc,
+    time::Duration,
 };
 
 use clap::Parser;
@@ -8,32 +10,44 @@ use futures::StreamExt;
 use lapin::{
     BasicProperties, Connection, ConnectionProperties,
     options::{
-        BasicAckOptions, BasicCancelOptions, BasicConsumeOptions, BasicPublishOptions,
-        BasicQosOptions, QueueDeclareOptions,
+        BasicAckOptions, BasicCancelOptions, BasicConsumeOptions, BasicNackOptions,
+        BasicPublishOptions, BasicQosOptions, QueueDeclareOptions,
     },
     types::FieldTable,
     uri::AMQPUri,
 };
 use log::{debug, error};
-use mangapill_scraper::{errors::ScraperErrors, models::Chapter};
+use mangapill_scraper::{
+    errors::ScraperErrors,
+    models::{Chapter, DownloadConfig, FailedChapter},
+};
 use reqwest::{
     Client, ClientBuilder,
     header::{self, HeaderMap, HeaderValue},
 };
 use rkyv::rancor;
 use scraper::Selector;
-use tokio::{fs, io::AsyncWriteExt};
+use tokio::{fs, io::AsyncWriteExt, sync::Semaphore, time::sleep};
 
 async fn download_file(
     client: &Client,
     url: &str,
     chapter_path: &Path,
     page_num: usize,
+    config: &DownloadConfig,
 ) -> anyhow::Result<()> {
+    let file_path = chapter_path.join(format!("{page_num:03}.jpg"));
+
+    // In resume mode a non-empty page file is treated as already downloaded.
+    if config.resume && fs::metadata(&file_path).await.is_ok_and(|meta| meta.len() > 0) {
+        return Ok(());
+    }
+
+    // Bound the number of in-flight image downloads across every chapter task.
+    let _permit = config.semaphore.acquire().await?;
     let fetch_image = async move || client.get(url).send().await?.bytes().await;
     match fetch_image().await {
         Ok(data) => {
-            let file_path = chapter_path.join(format!("{page_num:03}.jpg"));
             let mut downloaded_file = fs::File::create(file_path).await?;
             downloaded_file.write_all(&data).await?;
 
@@ -48,10 +62,29 @@ async fn download_file(
     }
 }
 
+/// Counts the non-empty `NNN.jpg` page files already present in a chapter
+/// directory, used to short-circuit chapters that are fully downloaded.
+async fn count_valid_pages(chapter_path: &Path) -> usize {
+    let Ok(mut entries) = fs::read_dir(chapter_path).await else {
+        return 0;
+    };
+    let mut count = 0;
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let path = entry.path();
+        if path.extension().is_some_and(|ext| ext == "jpg")
+            && entry.metadata().await.is_ok_and(|meta| meta.len() > 0)
+        {
+            count += 1;
+        }
+    }
+    count
+}
+
 async fn download_chapter(
     client: &Client,
     chapter_url: &str,
     chapter_path: &Path,
+    config: &DownloadConfig,
 ) -> anyhow::Result<Vec<usize>> {
     async fn fetch_image_urls(client: &Client, chapter_url: &str) -> anyhow::Result<Vec<String>> {
         let html_content = client.get(chapter_url).send().await?.text().await?;
@@ -75,43 +108,84 @@ async fn download_chapter(
             .collect::<Vec<String>>())
     }
 
-    let image_urls = fetch_image_urls(client, chapter_url).await?;
-
-    let tasks = futures::stream::iter(image_urls)
+    // Pages still to fetch, kept as `(page_num, url)` so a failed page can be
+    // re-fed into a fresh stream on the next retry round.
+    let mut pending: Vec<(usize, String)> = fetch_image_urls(client, chapter_url)
+        .await?
+        .into_iter()
         .enumerate()
-        .map(|(page_num, page_url)| async move {
-            download_file(client, &page_url, chapter_path, page_num).await
-        })
-        .buffer_unordered(6);
+        .collect();
+    let total_pages = pending.len();
 
-    let results = tasks.collect::<Vec<_>>().await;
-    let failed_pages = results
-        .into_iter()
-        .filter_map(|result| -> Option<usize> {
-            match result {
-                Err(err) => {
-                    if let Ok(ScraperErrors::PageDownloadFailed { page_num, .. }) = err.downcast() {
-                        Some(page_num)
-                    } else {
-                        None
-                    }
+    // Skip chapters whose pages are all already on disk.
+    if config.resume && total_pages > 0 && count_valid_pages(chapter_path).await >= total_pages {
+        return Ok(vec![]);
+    }
+
+    let mut round = 0;
+    loop {
+        let results = futures::stream::iter(std::mem::take(&mut pending))
+            .map(|(page_num, page_url)| async move {
+                match download_file(client, &page_url, chapter_path, page_num, config).await {
+                    Ok(()) => None,
+                    Err(_) => Some((page_num, page_url)),
                 }
-                Ok(_) => None,
-            }
-        })
-        .collect();
+            })
+            .buffer_unordered(config.max_conn);
+
+        pending = results.collect::<Vec<_>>().await.into_iter().flatten().collect();
+
+        if pending.is_empty() || round >= config.retries {
+            break;
+        }
+
+        let backoff = RETRY_BACKOFF[round.min(RETRY_BACKOFF.len() - 1)];
+        debug!(
+            "retrying {} failed pages in {}s (round {})",
+            pending.len(),
+            (config.base_delay * backoff).as_secs(),
+            round + 1
+        );
+        sleep(config.base_delay * backoff).await;
+        round += 1;
+    }
 
-    Ok(failed_pages)
+    Ok(pending.into_iter().map(|(page_num, _)| page_num).collect())
 }
 
 #[derive(Parser, Debug, Clone)]
 struct Args {
     #[arg(required = true, help = "path of the output directory")]
     manga_path: String,
+
+    #[arg(
+        long,
+        default_value_t = 8,
+        value_parser = clap::value_parser!(usize).range(1..),
+        help = "maximum concurrent image downloads across all chapters"
+    )]
+    max_conn: usize,
+
+    #[arg(long, default_value_t = 3, help = "times to re-attempt a failed page")]
+    retries: usize,
+
+    #[arg(
+        long,
+        default_value_t = 1000,
+        help = "base wait between retry rounds in milliseconds"
+    )]
+    base_delay: u64,
+
+    #[arg(long, help = "skip pages and chapters already downloaded")]
+    resume: bool,
 }
 
 const HOST_URL: &str = "https://mangapill.com";
 
+/// Multipliers applied to the base retry delay for successive retry rounds,
+/// yielding the ~1s / ~5s / ~30s tiers used by sibling manga downloaders.
+const RETRY_BACKOFF: [u32; 3] = [1, 5, 30];
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let args = Args::parse();
@@ -138,6 +212,16 @@ async fn main() -> anyhow::Result<()> {
             FieldTable::default(),
         )
         .await?;
+    send_channel
+        .queue_declare(
+            "chapter_failed_queue",
+            QueueDeclareOptions {
+                durable: true,
+                ..Default::default()
+            },
+            FieldTable::default(),
+        )
+        .await?;
 
     const QUEUE_NAME: &str = "chapter_queue";
 
@@ -172,37 +256,120 @@ async fn main() -> anyhow::Result<()> {
         .user_agent("Mozilla/5.0 (X11; Linux x86_64; rv:145.0) Gecko/20100101 Firefox/145.0")
         .build()?;
 
+    let config = DownloadConfig {
+        retries: args.retries,
+        base_delay: Duration::from_millis(args.base_delay),
+        max_conn: args.max_conn,
+        semaphore: Arc::new(Semaphore::new(args.max_conn)),
+        resume: args.resume,
+    };
+
     while let Some(delivery) = consumer.next().await {
         match delivery {
-            Ok(delivery) => {
-                if let Ok(
-                    chapter @ Chapter {
+            Ok(delivery) => match rkyv::from_bytes::<Chapter, rancor::Error>(&delivery.data) {
+                Ok(chapter) => {
+                    let Chapter {
                         url,
                         title: chapter_title,
-                    },
-                ) = &rkyv::from_bytes::<Chapter, rancor::Error>(&delivery.data)
-                {
+                    } = &chapter;
                     let chapter_url = format!("{HOST_URL}{url}");
-                    let _failed_pages =
-                        download_chapter(&client, &chapter_url, &manga_path.join(chapter_title))
-                            .await?;
+                    let failed_pages = match download_chapter(
+                        &client,
+                        &chapter_url,
+                        &manga_path.join(chapter_title),
+                        &config,
+                    )
+                    .await
+                    {
+                        Ok(failed_pages) => failed_pages,
+                        Err(err) => {
+                            // A transient chapter-level failure (e.g. the
+                            // page-list fetch) must not terminate the consume
+                            // loop. Record it to the failed queue like the
+                            // partial-download path, with empty `failed_pages`
+                            // since no page count is known, before rejecting the
+                            // delivery so a network blip can't silently drop it.
+                            error!("failed to download chapter {chapter_title}: {err}");
+                            let record = FailedChapter {
+                                chapter: chapter.clone(),
+                                failed_pages: vec![],
+                            };
+                            send_channel
+                                .basic_publish(
+                                    "",
+                                    "chapter_failed_queue",
+                                    BasicPublishOptions::default(),
+                                    &rkyv::to_bytes::<rancor::Error>(&record).unwrap(),
+                                    BasicProperties::default().with_delivery_mode(2),
+                                )
+                                .await?
+                                .await?;
+                            delivery
+                                .nack(BasicNackOptions {
+                                    requeue: false,
+                                    ..Default::default()
+                                })
+                                .await?;
+                            continue;
+                        }
+                    };
 
-                    delivery.ack(BasicAckOptions::default()).await?;
-                    send_channel
-                        .basic_publish(
-                            "",
-                            "chapter_completed_queue",
-                            BasicPublishOptions::default(),
-                            &rkyv::to_bytes::<rancor::Error>(chapter).unwrap(),
-                            BasicProperties::default().with_delivery_mode(2),
-                        )
-                        .await?
+                    if failed_pages.is_empty() {
+                        delivery.ack(BasicAckOptions::default()).await?;
+                        send_channel
+                            .basic_publish(
+                                "",
+                                "chapter_completed_queue",
+                                BasicPublishOptions::default(),
+                                &rkyv::to_bytes::<rancor::Error>(&chapter).unwrap(),
+                                BasicProperties::default().with_delivery_mode(2),
+                            )
+                            .await?
+                            .await?;
+                    } else {
+                        error!(
+                            "{}",
+                            ScraperErrors::ChapterDownloadFailed {
+                                title: chapter_title.clone(),
+                                failed_pages: failed_pages.clone(),
+                            }
+                        );
+                        let record = FailedChapter {
+                            chapter: chapter.clone(),
+                            failed_pages,
+                        };
+                        send_channel
+                            .basic_publish(
+                                "",
+                                "chapter_failed_queue",
+                                BasicPublishOptions::default(),
+                                &rkyv::to_bytes::<rancor::Error>(&record).unwrap(),
+                                BasicProperties::default().with_delivery_mode(2),
+                            )
+                            .await?
+                            .await?;
+                        // The failure is preserved in chapter_failed_queue above;
+                        // drop the original delivery without requeueing it.
+                        delivery
+                            .nack(BasicNackOptions {
+                                requeue: false,
+                                ..Default::default()
+                            })
+                            .await?;
+                    }
+                }
+                Err(err) => {
+                    // A delivery we can't deserialize is dead-lettered rather than
+                    // silently terminating the consume loop.
+                    error!("failed to deserialize delivery: {err}");
+                    delivery
+                        .nack(BasicNackOptions {
+                            requeue: false,
+                            ..Default::default()
+                        })
                         .await?;
-                } else {
-                    delivery.ack(BasicAckOptions::default()).await?;
-                    break;
                 }
-            }
+            },
             Err(err) => {
                 eprintln!("{err}");
                 break;
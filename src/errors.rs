@@ -11,6 +11,12 @@ pub enum ScraperErrors {
         page_num: usize,
     },
 
+    #[error("{title}: failed to download {} pages", failed_pages.len())]
+    ChapterDownloadFailed {
+        title: String,
+        failed_pages: Vec<usize>,
+    },
+
     #[error("failed to get title for id: {0}")]
     InvalidBookId(usize),
 
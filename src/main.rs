@@ -1,4 +1,4 @@
-use std::{fmt::Display, io::Write, path::Path, time::Duration};
+use std::{fmt::Display, io::Write, path::Path, sync::Arc, time::Duration};
 
 use chrono::Local;
 use clap::Parser;
@@ -11,10 +11,14 @@ use reqwest::{
     header::{self, HeaderMap, HeaderValue},
 };
 use scraper::Selector;
-use tokio::{fs, io::AsyncWriteExt, time::sleep};
+use tabled::{Table, Tabled};
+use tokio::{fs, io::AsyncWriteExt, sync::Semaphore, time::sleep};
 
-use crate::errors::ScraperErrors;
+use mangapill_scraper::models::DownloadConfig;
 
+use crate::{archive::OutputFormat, errors::ScraperErrors};
+
+mod archive;
 mod errors;
 
 async fn download_file(
@@ -23,11 +27,21 @@ async fn download_file(
     chapter_path: &Path,
     page_num: usize,
     progress_bar: &ProgressBar,
+    config: &DownloadConfig,
 ) -> anyhow::Result<()> {
+    let file_path = chapter_path.join(format!("{page_num:03}.jpg"));
+
+    // In resume mode a non-empty page file is treated as already downloaded.
+    if config.resume && fs::metadata(&file_path).await.is_ok_and(|meta| meta.len() > 0) {
+        progress_bar.tick();
+        return Ok(());
+    }
+
+    // Bound the number of in-flight image downloads across every chapter task.
+    let _permit = config.semaphore.acquire().await?;
     let fetch_image = async move || client.get(url).send().await?.bytes().await;
     match fetch_image().await {
         Ok(data) => {
-            let file_path = chapter_path.join(format!("{page_num:03}.jpg"));
             let mut downloaded_file = fs::File::create(file_path).await?;
             downloaded_file.write_all(&data).await?;
             progress_bar.tick();
@@ -43,12 +57,31 @@ async fn download_file(
     }
 }
 
+/// Counts the non-empty `NNN.jpg` page files already present in a chapter
+/// directory, used to short-circuit chapters that are fully downloaded.
+async fn count_valid_pages(chapter_path: &Path) -> usize {
+    let Ok(mut entries) = fs::read_dir(chapter_path).await else {
+        return 0;
+    };
+    let mut count = 0;
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let path = entry.path();
+        if path.extension().is_some_and(|ext| ext == "jpg")
+            && entry.metadata().await.is_ok_and(|meta| meta.len() > 0)
+        {
+            count += 1;
+        }
+    }
+    count
+}
+
 async fn download_chapter(
     client: &Client,
     chapter_url: &str,
     chapter_path: &Path,
     progress_bar: &ProgressBar,
-) -> anyhow::Result<Vec<usize>> {
+    config: &DownloadConfig,
+) -> anyhow::Result<(usize, Vec<usize>)> {
     async fn fetch_image_urls(client: &Client, chapter_url: &str) -> anyhow::Result<Vec<String>> {
         let html_content = client.get(chapter_url).send().await?.text().await?;
 
@@ -71,33 +104,54 @@ async fn download_chapter(
             .collect::<Vec<String>>())
     }
 
-    let image_urls = fetch_image_urls(client, chapter_url).await?;
-
-    let tasks = futures::stream::iter(image_urls)
+    // Pages still to fetch, kept as `(page_num, url)` so a failed page can be
+    // re-fed into a fresh stream on the next retry round.
+    let mut pending: Vec<(usize, String)> = fetch_image_urls(client, chapter_url)
+        .await?
+        .into_iter()
         .enumerate()
-        .map(|(page_num, page_url)| async move {
-            download_file(client, &page_url, chapter_path, page_num, progress_bar).await
-        })
-        .buffer_unordered(6);
+        .collect();
+    let total_pages = pending.len();
 
-    let results = tasks.collect::<Vec<_>>().await;
-    let failed_pages = results
-        .into_iter()
-        .filter_map(|result| -> Option<usize> {
-            match result {
-                Err(err) => {
-                    if let Ok(ScraperErrors::PageDownloadFailed { page_num, .. }) = err.downcast() {
-                        Some(page_num)
-                    } else {
-                        None
-                    }
+    // Skip chapters whose pages are all already on disk.
+    if config.resume && total_pages > 0 && count_valid_pages(chapter_path).await >= total_pages {
+        return Ok((total_pages, vec![]));
+    }
+
+    let mut round = 0;
+    loop {
+        let results = futures::stream::iter(std::mem::take(&mut pending))
+            .map(|(page_num, page_url)| async move {
+                match download_file(client, &page_url, chapter_path, page_num, progress_bar, config)
+                    .await
+                {
+                    Ok(()) => None,
+                    Err(_) => Some((page_num, page_url)),
                 }
-                Ok(_) => None,
-            }
-        })
-        .collect();
+            })
+            .buffer_unordered(config.max_conn);
+
+        pending = results.collect::<Vec<_>>().await.into_iter().flatten().collect();
+
+        if pending.is_empty() || round >= config.retries {
+            break;
+        }
 
-    Ok(failed_pages)
+        let backoff = RETRY_BACKOFF[round.min(RETRY_BACKOFF.len() - 1)];
+        debug!(
+            "retrying {} failed pages in {}s (round {})",
+            pending.len(),
+            (config.base_delay * backoff).as_secs(),
+            round + 1
+        );
+        sleep(config.base_delay * backoff).await;
+        round += 1;
+    }
+
+    Ok((
+        total_pages,
+        pending.into_iter().map(|(page_num, _)| page_num).collect(),
+    ))
 }
 
 #[derive(Debug, Clone)]
@@ -192,6 +246,45 @@ async fn get_manga_display_name(client: &Client, url: &str) -> anyhow::Result<Op
         .map(|e| e.text().collect::<String>()))
 }
 
+/// Extracts the trailing numeric chapter id that `fetch_chapters_urls` appends
+/// to each `Chapter.title` (e.g. `"one piece 0012.5"` -> `12.5`).
+fn parse_chapter_number(title: &str) -> Option<f64> {
+    let suffix: String = title
+        .chars()
+        .rev()
+        .take_while(|c| c.is_ascii_digit() || *c == '.')
+        .collect();
+    suffix.chars().rev().collect::<String>().parse().ok()
+}
+
+/// Filters `chapters` down to those whose number matches a `--chapters`
+/// selector such as `5,7-12,15`, each token being a single number or an
+/// inclusive `start-end` range.
+fn filter_chapters(chapters: Vec<Chapter>, spec: &[String]) -> Vec<Chapter> {
+    let mut ranges: Vec<(f64, f64)> = Vec::with_capacity(spec.len());
+    for token in spec {
+        let range = match token.split_once('-') {
+            Some((start, end)) => start.trim().parse().ok().zip(end.trim().parse().ok()),
+            None => token.trim().parse().ok().map(|number| (number, number)),
+        };
+        match range {
+            Some(range) => ranges.push(range),
+            None => {
+                error!("ignoring malformed --chapters token: {token:?}");
+                eprintln!("ignoring malformed --chapters token: {token:?}");
+            }
+        }
+    }
+
+    chapters
+        .into_iter()
+        .filter(|chapter| {
+            parse_chapter_number(&chapter.title)
+                .is_some_and(|number| ranges.iter().any(|&(lo, hi)| number >= lo && number <= hi))
+        })
+        .collect()
+}
+
 fn select_chapters(mut chapters: Vec<Chapter>) -> Vec<Chapter> {
     let selection_theme = ColorfulTheme {
         prompt_style: Style::default().blue(),
@@ -216,15 +309,25 @@ fn select_chapters(mut chapters: Vec<Chapter>) -> Vec<Chapter> {
     chapters
 }
 
+/// A chapter that did not download every page, with enough detail to tell a
+/// fully-failed chapter apart from one merely missing a few pages.
+#[derive(Debug, Clone)]
+struct ChapterReport {
+    chapter: Chapter,
+    failed_pages: Vec<usize>,
+    total_pages: usize,
+}
+
 async fn download_chapters(
     client: &Client,
     chapters: &Vec<Chapter>,
     manga_path: &Path,
     chapter_progress: &ProgressBar,
     total_progress: &ProgressBar,
-) -> Vec<Chapter> {
+    config: &DownloadConfig,
+) -> Vec<ChapterReport> {
     chapter_progress.tick();
-    let mut failed_chapter_downloads = vec![];
+    let mut partial_downloads = vec![];
 
     for chapter @ Chapter {
         url,
@@ -239,22 +342,31 @@ async fn download_chapters(
             &chapter_url,
             &manga_path.join(chapter_title),
             chapter_progress,
+            config,
         )
         .await
         {
-            Ok(failed_pages) => {
+            Ok((total_pages, failed_pages)) => {
                 if !failed_pages.is_empty() {
                     error!(
                         "{chapter_title}: failed to download {} pages",
                         failed_pages.len()
                     );
-                    failed_chapter_downloads.push(chapter.clone());
+                    partial_downloads.push(ChapterReport {
+                        chapter: chapter.clone(),
+                        failed_pages,
+                        total_pages,
+                    });
                 }
             }
             Err(err) => {
                 debug!("{err}");
                 error!("failed to fetch: {url}");
-                failed_chapter_downloads.push(chapter.clone());
+                partial_downloads.push(ChapterReport {
+                    chapter: chapter.clone(),
+                    failed_pages: vec![],
+                    total_pages: 0,
+                });
             }
         };
 
@@ -264,7 +376,7 @@ async fn download_chapters(
 
     chapter_progress.finish();
 
-    failed_chapter_downloads
+    partial_downloads
 }
 
 #[derive(Parser, Debug, Clone)]
@@ -277,10 +389,67 @@ struct Args {
 
     #[arg(short = 'j', long, default_value_t = 1)]
     threads: usize,
+
+    #[arg(
+        long,
+        value_delimiter = ',',
+        help = "chapters to download non-interactively, e.g. 5,7-12,15"
+    )]
+    chapters: Option<Vec<String>>,
+
+    #[arg(long, help = "download every chapter non-interactively")]
+    all: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = OutputFormat::Dir,
+        help = "how to bundle each downloaded chapter"
+    )]
+    output_format: OutputFormat,
+
+    #[arg(long, help = "bundle all selected chapters into a single archive")]
+    merge: bool,
+
+    #[arg(
+        long,
+        default_value_t = 8,
+        value_parser = clap::value_parser!(usize).range(1..),
+        help = "maximum concurrent image downloads across all chapters"
+    )]
+    max_conn: usize,
+
+    #[arg(long, default_value_t = 3, help = "times to re-attempt a failed page")]
+    retries: usize,
+
+    #[arg(
+        long,
+        default_value_t = 1000,
+        help = "base wait between retry rounds in milliseconds"
+    )]
+    base_delay: u64,
+
+    #[arg(long, help = "skip pages and chapters already downloaded")]
+    resume: bool,
+}
+
+/// A row of the end-of-run summary table listing a partially-downloaded chapter.
+#[derive(Tabled)]
+struct PartialDownloadRow {
+    #[tabled(rename = "Chapter")]
+    chapter: String,
+    #[tabled(rename = "Failed Pages")]
+    failed_pages: String,
+    #[tabled(rename = "Total Pages")]
+    total_pages: String,
 }
 
 const HOST_URL: &str = "https://mangapill.com";
 
+/// Multipliers applied to the base retry delay for successive retry rounds,
+/// yielding the ~1s / ~5s / ~30s tiers used by sibling manga downloaders.
+const RETRY_BACKOFF: [u32; 3] = [1, 5, 30];
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let log_target = Box::new(std::fs::File::create("log.txt").expect("Failed to create log.txt"));
@@ -320,7 +489,6 @@ async fn main() -> anyhow::Result<()> {
         .await
         .unwrap_or_else(|_| Some(title.clone()))
         .unwrap();
-    println!("Select chapters to download for {display_title}");
 
     let all_chapters = match fetch_chapters_urls(&client, title_url.as_ref()).await {
         Ok(chapters) => {
@@ -339,11 +507,22 @@ async fn main() -> anyhow::Result<()> {
         }
     };
 
-    let mut selected_chapters = select_chapters(all_chapters);
+    let mut selected_chapters = if args.all {
+        all_chapters
+    } else if let Some(spec) = &args.chapters {
+        filter_chapters(all_chapters, spec)
+    } else {
+        println!("Select chapters to download for {display_title}");
+        select_chapters(all_chapters)
+    };
 
     let book_path = Path::new("tmp").join(title);
 
     let num_chapters = selected_chapters.len();
+    let chapter_titles: Vec<String> = selected_chapters
+        .iter()
+        .map(|Chapter { title, .. }| title.clone())
+        .collect();
     for Chapter { title, .. } in &selected_chapters {
         fs::create_dir_all(book_path.join(title))
             .await
@@ -372,6 +551,14 @@ async fn main() -> anyhow::Result<()> {
     let num_threads = args.threads.min(num_chapters);
     let batches = split_jobs(&mut selected_chapters, num_threads);
 
+    let config = DownloadConfig {
+        retries: args.retries,
+        base_delay: Duration::from_millis(args.base_delay),
+        max_conn: args.max_conn,
+        semaphore: Arc::new(Semaphore::new(args.max_conn)),
+        resume: args.resume,
+    };
+
     let mut tasks: Vec<_> = vec![];
     for batch in batches {
         let client = client.clone();
@@ -384,6 +571,7 @@ async fn main() -> anyhow::Result<()> {
         );
 
         let total_progress = total_progress.clone();
+        let config = config.clone();
 
         tasks.push(tokio::spawn(async move {
             let failed_chapters = download_chapters(
@@ -392,6 +580,7 @@ async fn main() -> anyhow::Result<()> {
                 &book_path,
                 &chapter_progress,
                 &total_progress,
+                &config,
             )
             .await;
             chapter_progress.finish_and_clear();
@@ -399,12 +588,12 @@ async fn main() -> anyhow::Result<()> {
             failed_chapters
         }));
     }
-    let mut all_failed_chapters = vec![];
+    let mut partial_downloads: Vec<ChapterReport> = vec![];
     let start_time = Local::now();
     for task in tasks {
         match task.await {
-            Ok(failed_chapters) => {
-                all_failed_chapters.extend(failed_chapters);
+            Ok(reports) => {
+                partial_downloads.extend(reports);
             }
             Err(e) => {
                 error!("{e}");
@@ -417,17 +606,47 @@ async fn main() -> anyhow::Result<()> {
         "finished downloading in {:.6} seconds",
         download_duration.as_seconds_f64()
     );
-    if !all_failed_chapters.is_empty() {
+    if !partial_downloads.is_empty() {
         info!(
             "failed to fully download chapters: [{}]",
-            all_failed_chapters
+            partial_downloads
                 .iter()
-                .map(|Chapter { title, .. }| title.to_string())
+                .map(|report| report.chapter.title.clone())
                 .collect::<Vec<_>>()
                 .join(", ")
         );
     }
 
+    if args.output_format != OutputFormat::Dir {
+        if let Err(err) = archive::package(
+            &book_path,
+            &chapter_titles,
+            &display_title,
+            args.output_format,
+            args.merge,
+        ) {
+            debug!("{err}");
+            error!("failed to package chapters: {err}");
+        }
+    }
+
+    if !partial_downloads.is_empty() {
+        let rows = partial_downloads.iter().map(|report| {
+            let (failed, total) = if report.total_pages == 0 {
+                ("all".to_string(), "?".to_string())
+            } else {
+                (report.failed_pages.len().to_string(), report.total_pages.to_string())
+            };
+            PartialDownloadRow {
+                chapter: report.chapter.title.clone(),
+                failed_pages: failed,
+                total_pages: total,
+            }
+        });
+        println!("\nChapters not fully downloaded:");
+        println!("{}", Table::new(rows));
+    }
+
     total_progress.finish();
     println!(
         "Downloaded {num_chapters} {} in {duration:.2} seconds to {path}/",
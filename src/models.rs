@@ -1,6 +1,7 @@
-use std::fmt::Display;
+use std::{fmt::Display, sync::Arc, time::Duration};
 
 use rkyv::{Archive, Deserialize, Serialize};
+use tokio::sync::Semaphore;
 
 #[derive(Debug, Clone, Archive, Serialize, Deserialize)]
 pub struct Chapter {
@@ -13,3 +14,27 @@ impl Display for Chapter {
         write!(f, "{}", self.title)
     }
 }
+
+/// Knobs controlling how a chapter's pages are fetched, shared by the CLI and
+/// the queue worker so the retry, concurrency and resume behavior stay in sync.
+#[derive(Debug, Clone)]
+pub struct DownloadConfig {
+    /// Times a failed page is re-attempted before it is reported as failed.
+    pub retries: usize,
+    /// Base wait between retry rounds, scaled up by the backoff tiers.
+    pub base_delay: Duration,
+    /// Maximum number of pages streamed concurrently per chapter.
+    pub max_conn: usize,
+    /// Caps in-flight image downloads across every chapter task.
+    pub semaphore: Arc<Semaphore>,
+    /// When set, pages and chapters already on disk are skipped.
+    pub resume: bool,
+}
+
+/// A structured record of a chapter whose pages did not all download, published
+/// to the dead-letter queue so the producer can see partial failures.
+#[derive(Debug, Clone, Archive, Serialize, Deserialize)]
+pub struct FailedChapter {
+    pub chapter: Chapter,
+    pub failed_pages: Vec<usize>,
+}